@@ -3,16 +3,60 @@
 //! It includes structures for certificate building, site-specific certificate building,
 //! and certificate management. The module uses OpenSSL for cryptographic operations.
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use openssl::{
     asn1::Asn1Time,
-    hash::MessageDigest,
-    x509::{X509Builder, X509},
+    bn::{BigNum, MsbOption},
+    nid::Nid,
+    stack::Stack,
+    x509::{
+        extension::SubjectKeyIdentifier, store::X509StoreBuilder, X509Builder, X509Req,
+        X509StoreContext, X509,
+    },
 };
+use serde::Deserialize;
 use std::ops::{Deref, DerefMut};
 
+use crate::crl;
 use crate::key::Key;
 
+/// The intended use of a certificate.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Purpose {
+    Server,
+    Client,
+    Both,
+}
+
+/// Appends the `ExtendedKeyUsage`/`KeyUsage` extensions matching `purpose` to a builder.
+/// Shared by every issuance path (generated keys and externally supplied CSRs alike) so the
+/// two can't drift apart.
+fn append_purpose_extensions(builder: &mut X509Builder, purpose: Purpose) -> Result<()> {
+    let mut eku = openssl::x509::extension::ExtendedKeyUsage::new();
+    match purpose {
+        Purpose::Server => {
+            eku.server_auth();
+        }
+        Purpose::Client => {
+            eku.client_auth();
+        }
+        Purpose::Both => {
+            eku.server_auth().client_auth();
+        }
+    }
+    builder.append_extension(eku.build()?)?;
+
+    let mut usage = openssl::x509::extension::KeyUsage::new();
+    usage.critical().digital_signature();
+    if !matches!(purpose, Purpose::Client) {
+        usage.key_encipherment();
+    }
+    builder.append_extension(usage.build()?)?;
+
+    Ok(())
+}
+
 /// A builder for X.509 certificates.
 pub struct CertificateBuilder(X509Builder, Key);
 
@@ -37,6 +81,13 @@ impl CertificateBuilder {
         builder.set_version(2)?;
         builder.set_pubkey(&key)?;
 
+        let skid = SubjectKeyIdentifier::new().build(&builder.x509v3_context(None, None))?;
+        builder.append_extension(skid)?;
+
+        let mut serial = BigNum::new()?;
+        serial.rand(159, MsbOption::MAYBE_ZERO, false)?;
+        builder.set_serial_number(&serial.to_asn1_integer()?)?;
+
         Ok(Self(builder, key))
     }
 
@@ -51,9 +102,8 @@ impl CertificateBuilder {
 
     /// Signs the certificate with its own key.
     pub fn sign_self(&mut self) -> Result<()> {
-        self.0
-            .sign(&self.1, MessageDigest::sha256())
-            .map_err(Into::into)
+        let digest = self.1.signing_digest();
+        self.0.sign(&self.1, digest).map_err(Into::into)
     }
 
     /// Builds the certificate.
@@ -61,24 +111,38 @@ impl CertificateBuilder {
         Certificate(self.0.build(), self.1)
     }
 
-    /// Sets the certificate as a Certificate Authority.
-    pub fn set_certificate_authority(&mut self) -> Result<()> {
+    /// Sets the certificate as a Certificate Authority. `path_len` restricts how many
+    /// further CA certificates may appear below this one in the chain (`Some(0)` means
+    /// this CA may only sign leaf certificates, as intermediate issuing CAs do).
+    pub fn set_certificate_authority(&mut self, path_len: Option<u32>) -> Result<()> {
+        let mut constraints = openssl::x509::extension::BasicConstraints::new();
+        constraints.ca().critical();
+        if let Some(path_len) = path_len {
+            constraints.pathlen(path_len);
+        }
+
         self.0
-            .append_extension(
-                openssl::x509::extension::BasicConstraints::new()
-                    .ca()
-                    .build()?,
-            )
+            .append_extension(constraints.build()?)
             .map_err(Into::into)
     }
 
     /// Configures the certificate for server authentication and returns a SiteCertificateBuilder.
     pub fn set_server_auth(mut self) -> Result<SiteCertificateBuilder> {
-        self.0.append_extension(
-            openssl::x509::extension::ExtendedKeyUsage::new()
-                .server_auth()
-                .build()?,
-        )?;
+        append_purpose_extensions(&mut self.0, Purpose::Server)?;
+        Ok(SiteCertificateBuilder(self))
+    }
+
+    /// Configures the certificate for client authentication (mutual TLS) and returns a
+    /// SiteCertificateBuilder.
+    pub fn set_client_auth(mut self) -> Result<SiteCertificateBuilder> {
+        append_purpose_extensions(&mut self.0, Purpose::Client)?;
+        Ok(SiteCertificateBuilder(self))
+    }
+
+    /// Configures the certificate for both server and client authentication, for mTLS peers
+    /// that act as both ends of a tunnel.
+    pub fn set_server_and_client_auth(mut self) -> Result<SiteCertificateBuilder> {
+        append_purpose_extensions(&mut self.0, Purpose::Both)?;
         Ok(SiteCertificateBuilder(self))
     }
 }
@@ -117,8 +181,10 @@ impl SiteCertificateBuilder {
     }
 }
 
-/// Represents an X.509 certificate.
-pub struct Certificate(X509, Key);
+/// Represents an X.509 certificate. The private key is only absent for certificates issued
+/// from an externally supplied signing request, where the private half never touches this
+/// tool (see [`Certificate::sign_request`]).
+pub struct Certificate(X509, Option<Key>);
 
 impl Deref for Certificate {
     type Target = X509;
@@ -141,19 +207,146 @@ impl Certificate {
 
         let key = Key::load(&key_path)?;
 
-        Ok(Self(crt, key))
+        Ok(Self(crt, Some(key)))
+    }
+
+    /// Returns this certificate's private key, if it has one.
+    fn key(&self) -> Result<&Key> {
+        self.1
+            .as_ref()
+            .context("Certificate has no private key; it was issued from an external signing request")
     }
 
-    /// Saves a certificate and its corresponding key to files.
+    /// Saves a certificate and its corresponding key to files. If the certificate has no
+    /// private key, only the `.crt` file is written.
     pub fn save(&self, name: &str) -> Result<()> {
-        self.1.save(&format!("{}.key", name))?;
+        if let Some(key) = self.1.as_ref() {
+            key.save(&format!("{}.key", name))?;
+        }
         Ok(std::fs::write(&format!("{}.crt", &name), self.0.to_pem()?)?)
     }
 
+    /// Saves a certificate and its key, with the `.crt` file holding the full chain: this
+    /// certificate followed by each certificate in `chain`, issuer-first. This is what
+    /// servers expect when the leaf was issued by an intermediate rather than the root.
+    pub fn save_with_chain(&self, name: &str, chain: &[&Certificate]) -> Result<()> {
+        if let Some(key) = self.1.as_ref() {
+            key.save(&format!("{}.key", name))?;
+        }
+
+        let mut pem = self.0.to_pem()?;
+        for cert in chain {
+            pem.extend(cert.0.to_pem()?);
+        }
+
+        Ok(std::fs::write(&format!("{}.crt", &name), pem)?)
+    }
+
     /// Signs another certificate using this certificate's key.
     pub fn sign(&self, builder: &mut CertificateBuilder) -> Result<()> {
+        let akid = openssl::x509::extension::AuthorityKeyIdentifier::new()
+            .keyid(true)
+            .issuer(false)
+            .build(&builder.x509v3_context(Some(&self.0), None))?;
+        builder.append_extension(akid)?;
+
+        let key = self.key()?;
+        builder.sign(key, key.signing_digest()).map_err(Into::into)
+    }
+
+    /// Signs an externally supplied certificate signing request, copying its subject name and
+    /// requested Subject Alternative Names (no other requested extension is copied) rather
+    /// than generating a key or subject of our own. Used for certifying keys whose private half was generated elsewhere
+    /// (HSMs, other machines) and should never be exported to this tool. `purpose` and
+    /// `crl_url` are applied exactly as they are for generated-key certificates, so a
+    /// CSR-issued certificate is never missing the KeyUsage/ExtendedKeyUsage or CRL
+    /// Distribution Point extensions its siblings get.
+    pub fn sign_request(
+        &self,
+        req: &X509Req,
+        days: u32,
+        purpose: Purpose,
+        crl_url: Option<&str>,
+    ) -> Result<Certificate> {
+        req.verify(&req.public_key()?)
+            .context("Certificate signing request failed self-signature verification")?;
+
+        let mut builder = X509Builder::new()?;
+        builder.set_version(2)?;
+        builder.set_pubkey(&req.public_key()?)?;
+        builder.set_subject_name(req.subject_name())?;
+        builder.set_issuer_name(self.0.subject_name())?;
+
+        let mut serial = BigNum::new()?;
+        serial.rand(159, MsbOption::MAYBE_ZERO, false)?;
+        builder.set_serial_number(&serial.to_asn1_integer()?)?;
+
+        builder.set_not_before(Asn1Time::days_from_now(0)?.as_ref())?;
+        builder.set_not_after(Asn1Time::days_from_now(days)?.as_ref())?;
+
+        let skid = SubjectKeyIdentifier::new().build(&builder.x509v3_context(Some(&self.0), None))?;
+        builder.append_extension(skid)?;
+        let akid = openssl::x509::extension::AuthorityKeyIdentifier::new()
+            .keyid(true)
+            .issuer(false)
+            .build(&builder.x509v3_context(Some(&self.0), None))?;
+        builder.append_extension(akid)?;
+
+        append_purpose_extensions(&mut builder, purpose)?;
+        if let Some(crl_url) = crl_url {
+            builder.append_extension(crl::crl_distribution_point_extension(crl_url)?)?;
+        }
+
+        // Only the requested Subject Alternative Names are honored. A CSR is untrusted
+        // external input, so requested `basicConstraints`/`keyUsage`/`extendedKeyUsage` or
+        // key-identifier extensions must not be copied onto the issued certificate: doing
+        // so would let a CSR request CA rights or other usages our CA never intended to
+        // grant, and would collide with the SKID/AKID/purpose extensions already appended
+        // above.
+        for extension in req.extensions()?.iter() {
+            if extension.object().nid() == Nid::SUBJECT_ALT_NAME {
+                builder.append_extension2(extension)?;
+            }
+        }
+
+        let key = self.key()?;
+        builder.sign(key, key.signing_digest())?;
+
+        Ok(Certificate(builder.build(), None))
+    }
+
+    /// Signs a certificate revocation list using this certificate's key.
+    pub fn sign_crl(&self, builder: &mut openssl::x509::X509CrlBuilder) -> Result<()> {
+        let key = self.key()?;
         builder
-            .sign(&self.1, MessageDigest::sha256())
+            .sign(key, key.signing_digest())
             .map_err(Into::into)
     }
+
+    /// Verifies that this certificate chains up to one of the given trusted roots, walking
+    /// through `chain` (e.g. the intermediate issuing CA that signed it, if any) without
+    /// trusting those certificates directly — only `roots` are trusted.
+    pub fn verify_against(&self, roots: &[&Certificate], chain: &[&Certificate]) -> Result<()> {
+        let mut store_builder = X509StoreBuilder::new()?;
+        for root in roots {
+            store_builder.add_cert(root.0.clone())?;
+        }
+        let store = store_builder.build();
+
+        let mut untrusted = Stack::new()?;
+        for cert in chain {
+            untrusted.push(cert.0.clone())?;
+        }
+
+        let mut context = X509StoreContext::new()?;
+        let (verified, depth, error) = context.init(&store, &self.0, &untrusted, |ctx| {
+            Ok((ctx.verify_cert()?, ctx.error_depth(), ctx.error()))
+        })?;
+
+        if !verified {
+            bail!("Certificate verification failed at depth {}: {}", depth, error);
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,117 @@
+//! This module provides functionality for tracking certificate revocations and for
+//! building the signed Certificate Revocation List (CRL) published alongside a CA.
+
+use anyhow::{Context, Result};
+use openssl::asn1::{Asn1Object, Asn1OctetString, Asn1Time};
+use openssl::bn::BigNum;
+use openssl::x509::{CrlReason, X509Crl, X509CrlBuilder, X509Extension, X509Name, X509RevokedBuilder};
+use serde::Deserialize;
+
+use crate::cert::Certificate;
+
+/// A single revoked certificate entry, as tracked in `revoked.toml`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct RevokedEntry {
+    /// Serial number of the revoked certificate, as a decimal string.
+    pub serial: String,
+    /// Date the certificate was revoked, e.g. `20260101000000Z`.
+    pub revoked_at: String,
+    /// Optional reason code, e.g. `key_compromise` or `superseded`.
+    pub reason: Option<String>,
+}
+
+/// The revocation list tracked for a single Certificate Authority.
+#[derive(Deserialize, Debug, Default)]
+pub struct RevocationList {
+    #[serde(default)]
+    pub revoked: Vec<RevokedEntry>,
+}
+
+impl RevocationList {
+    /// Loads the revocation list from `path`, or returns an empty list if the file does not
+    /// exist yet.
+    pub fn load(path: &str) -> Result<Self> {
+        if !std::fs::try_exists(path)? {
+            return Ok(Self::default());
+        }
+
+        let toml_str =
+            std::fs::read_to_string(path).with_context(|| format!("Error loading revocation list {}", path))?;
+        toml::from_str(&toml_str).with_context(|| format!("Error parsing revocation list {}", path))
+    }
+
+    /// Builds and signs the `X509Crl` for this revocation list using the given CA.
+    pub fn build(&self, ca: &Certificate, issuer: &X509Name, validity_days: u32) -> Result<X509Crl> {
+        let mut builder = X509CrlBuilder::new()?;
+        builder.set_issuer_name(issuer)?;
+        builder.set_last_update(Asn1Time::days_from_now(0)?.as_ref())?;
+        builder.set_next_update(Asn1Time::days_from_now(validity_days)?.as_ref())?;
+
+        for entry in &self.revoked {
+            let serial = BigNum::from_dec_str(&entry.serial)?.to_asn1_integer()?;
+            let revocation_date = Asn1Time::from_str(&entry.revoked_at)?;
+
+            let mut revoked = X509RevokedBuilder::new(&serial, &revocation_date)?;
+            if let Some(reason) = entry.reason.as_deref() {
+                revoked.add_reason(parse_reason(reason)?)?;
+            }
+            builder.add_revoked(revoked.build())?;
+        }
+
+        ca.sign_crl(&mut builder)?;
+        Ok(builder.build())
+    }
+}
+
+fn parse_reason(reason: &str) -> Result<CrlReason> {
+    Ok(match reason {
+        "unspecified" => CrlReason::UNSPECIFIED,
+        "key_compromise" => CrlReason::KEY_COMPROMISE,
+        "ca_compromise" => CrlReason::CA_COMPROMISE,
+        "affiliation_changed" => CrlReason::AFFILIATION_CHANGED,
+        "superseded" => CrlReason::SUPERSEDED,
+        "cessation_of_operation" => CrlReason::CESSATION_OF_OPERATION,
+        "certificate_hold" => CrlReason::CERTIFICATE_HOLD,
+        "remove_from_crl" => CrlReason::REMOVE_FROM_CRL,
+        other => anyhow::bail!("Unknown revocation reason: {}", other),
+    })
+}
+
+/// Builds a CRL Distribution Point extension (OID 2.5.29.31) pointing at `url`.
+///
+/// The openssl crate has no typed builder for this extension, so it is assembled by hand as a
+/// DER-encoded `DistributionPoint` sequence wrapping `url` as a URI `GeneralName`.
+pub fn crl_distribution_point_extension(url: &str) -> Result<X509Extension> {
+    // GeneralName ::= CHOICE { ..., uniformResourceIdentifier [6] IA5String, ... } — a
+    // context-tagged primitive, not constructed.
+    let general_name = der_tlv(0x86, url.as_bytes());
+    let full_name = der_tlv(0xa0, &general_name);
+    let distribution_point = der_tlv(0xa0, &full_name);
+    let distribution_points = der_tlv(0x30, &der_tlv(0x30, &distribution_point));
+
+    let der = Asn1OctetString::new_from_bytes(&distribution_points)?;
+    X509Extension::new_from_der(&Asn1Object::from_str("2.5.29.31")?, false, &der).map_err(Into::into)
+}
+
+fn der_length(len: usize) -> Vec<u8> {
+    if len < 0x80 {
+        vec![len as u8]
+    } else {
+        let bytes: Vec<u8> = len
+            .to_be_bytes()
+            .iter()
+            .skip_while(|&&b| b == 0)
+            .copied()
+            .collect();
+        let mut out = vec![0x80 | bytes.len() as u8];
+        out.extend(bytes);
+        out
+    }
+}
+
+fn der_tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    out.extend(der_length(content.len()));
+    out.extend_from_slice(content);
+    out
+}
@@ -1,7 +1,7 @@
-//! This module provides functionality for handling RSA private keys.
+//! This module provides functionality for handling private keys.
 //!
-//! It includes methods for loading, saving, generating, and managing RSA keys
-//! using the OpenSSL library.
+//! It includes methods for loading, saving, generating, and managing RSA, ECDSA and
+//! Ed25519 keys using the OpenSSL library.
 
 use std::fs::File;
 use std::io::Write;
@@ -9,15 +9,57 @@ use std::ops::Deref;
 
 use anyhow::{bail, Context, Result};
 use log::info;
-use openssl::pkey::{PKey, Private};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Private};
 use openssl::rsa::Rsa;
 use openssl::symm::Cipher;
+use serde::Deserialize;
 
 use crate::console::ask_passphrase;
 
 use super::console::confirm;
 
-/// Represents an RSA private key.
+/// The key algorithm (and size or curve) to generate.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub enum KeyType {
+    /// RSA with the given modulus size in bits, e.g. `rsa:4096`.
+    Rsa(u32),
+    /// ECDSA on the NIST P-256 curve, written as `ecdsa:p256`.
+    EcdsaP256,
+    /// Ed25519, written as `ed25519`.
+    Ed25519,
+}
+
+impl Default for KeyType {
+    fn default() -> Self {
+        KeyType::Rsa(2048)
+    }
+}
+
+impl TryFrom<String> for KeyType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        match value.as_str() {
+            "ed25519" => Ok(KeyType::Ed25519),
+            "ecdsa:p256" => Ok(KeyType::EcdsaP256),
+            other => {
+                let bits = other
+                    .strip_prefix("rsa:")
+                    .with_context(|| format!("Unknown key type: {}", other))?;
+                Ok(KeyType::Rsa(
+                    bits.parse()
+                        .with_context(|| format!("Invalid RSA key size: {}", bits))?,
+                ))
+            }
+        }
+    }
+}
+
+/// Represents a private key.
 pub struct Key(PKey<Private>);
 
 impl Deref for Key {
@@ -28,18 +70,29 @@ impl Deref for Key {
 }
 
 impl Key {
-    /// Loads an RSA private key from a PEM file.
+    /// Loads a private key from a PEM file.
     pub fn load(path: &str) -> Result<Self> {
         info!("Reading key file: {}", path);
         let pem_data =
             std::fs::read(path).with_context(|| format!("Error loading key file {}", path))?;
-        let rsa = Rsa::private_key_from_pem(&pem_data)?;
+        let key = PKey::private_key_from_pem(&pem_data)?;
 
         info!("Key file read OK");
-        Ok(Self(PKey::from_rsa(rsa)?))
+        Ok(Self(key))
+    }
+
+    /// Returns the digest to use when signing with this key. Ed25519 hashes the message as
+    /// part of the signature algorithm itself, so OpenSSL requires signing it with a null
+    /// digest rather than sha256.
+    pub fn signing_digest(&self) -> MessageDigest {
+        if self.0.id() == Id::ED25519 {
+            MessageDigest::null()
+        } else {
+            MessageDigest::sha256()
+        }
     }
 
-    /// Saves the RSA private key to a PEM file.
+    /// Saves the private key to a PEM file.
     pub fn save(&mut self, path: &str) -> Result<()> {
         let mut passphrase = None;
         loop {
@@ -70,15 +123,23 @@ impl Key {
         Ok(())
     }
 
-    /// Generates a new RSA private key.
-    pub fn generate() -> Result<Self> {
-        info!("Generating a new RSA key");
-        let rsa = Rsa::generate(2048)?;
-        Ok(Self(PKey::from_rsa(rsa)?))
+    /// Generates a new private key of the given type.
+    pub fn generate(key_type: KeyType) -> Result<Self> {
+        info!("Generating a new {:?} key", key_type);
+        let pkey = match key_type {
+            KeyType::Rsa(bits) => PKey::from_rsa(Rsa::generate(bits)?)?,
+            KeyType::EcdsaP256 => {
+                let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+                PKey::from_ec_key(EcKey::generate(&group)?)?
+            }
+            KeyType::Ed25519 => PKey::generate_ed25519()?,
+        };
+        Ok(Self(pkey))
     }
 
-    /// Loads an existing RSA private key or generates a new one if it doesn't exist.
-    pub fn load_or_generate(path: &str) -> Result<Self> {
+    /// Loads an existing private key or generates a new one of the given type if it doesn't
+    /// exist.
+    pub fn load_or_generate(path: &str, key_type: KeyType) -> Result<Self> {
         if let Ok(key) = Self::load(path) {
             info!("Key {} loaded OK", path);
             return Ok(key);
@@ -89,7 +150,7 @@ impl Key {
             bail!("Canceled by user");
         }
 
-        let mut key = Self::generate()?;
+        let mut key = Self::generate(key_type)?;
         key.save(path)?;
         Ok(key)
     }
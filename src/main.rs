@@ -2,6 +2,7 @@
 
 mod cert;
 mod console;
+mod crl;
 mod key;
 
 use std::collections::HashMap;
@@ -16,12 +17,18 @@ use log::info;
 
 use openssl::nid::Nid;
 use openssl::x509::X509Name;
+use openssl::x509::X509Req;
 use serde::Deserialize;
 
 use cert::Certificate;
 use cert::CertificateBuilder;
+use cert::Purpose;
 use console::confirm;
+use crl::RevocationList;
 use key::Key;
+use key::KeyType;
+
+const REVOCATION_LIST_FILE_NAME: &str = "revoked.toml";
 
 const CONFIG_FILE_NAME: &str = "certgen.toml";
 
@@ -42,6 +49,30 @@ struct NetworkCfg {
     root_ca_name: String,
     #[serde(default = "default_root_ca_validity_days")]
     root_ca_validity_days: u32,
+
+    /// URL site certificates should advertise as their CRL Distribution Point.
+    crl_url: Option<String>,
+    /// How long a freshly generated CRL is valid for before it needs refreshing. Kept short
+    /// and independent of `root_ca_validity_days` so revocations actually propagate.
+    #[serde(default = "default_crl_validity_days")]
+    crl_validity_days: u32,
+
+    /// Named intermediate issuing CAs, signed by the root. Sites reference these by key
+    /// via `SiteCfg::issuer`.
+    #[serde(default)]
+    cas: HashMap<String, CaCfg>,
+
+    /// Key algorithm used for the root CA, every intermediate CA, and every site that does
+    /// not set its own `SiteCfg::key_type`. Defaults to RSA-2048.
+    #[serde(default)]
+    key_type: KeyType,
+}
+
+#[derive(Deserialize, Debug)]
+struct CaCfg {
+    name: String,
+    #[serde(default = "default_root_ca_validity_days")]
+    validity_days: u32,
 }
 
 #[derive(Deserialize, Debug)]
@@ -50,6 +81,26 @@ struct SiteCfg {
     #[serde(default = "default_crt_validity_days")]
     crt_validity_days: u32,
     alt_names: Vec<String>,
+
+    /// Key into `NetworkCfg::cas` of the intermediate CA that should sign this site.
+    /// Unset means the site is signed directly by the root.
+    issuer: Option<String>,
+
+    /// What the certificate may be used for.
+    #[serde(default = "default_purpose")]
+    purpose: Purpose,
+
+    /// Path to a PEM-encoded certificate signing request. When set, this site's key and
+    /// subject name are taken from the CSR instead of being generated, so a key that never
+    /// touches this tool can still be certified.
+    csr: Option<String>,
+
+    /// Key algorithm for this site, overriding `NetworkCfg::key_type`.
+    key_type: Option<KeyType>,
+}
+
+fn default_purpose() -> Purpose {
+    Purpose::Server
 }
 
 fn default_root_ca_name() -> String {
@@ -60,6 +111,10 @@ fn default_crt_validity_days() -> u32 {
     365 * 2
 }
 
+fn default_crl_validity_days() -> u32 {
+    30
+}
+
 fn default_root_ca_validity_days() -> u32 {
     365 * 100
 }
@@ -84,15 +139,15 @@ impl Deref for Site {
 }
 
 impl Network {
-    fn build_subject_name(&self, site: Option<&Site>) -> Result<X509Name> {
+    /// Builds a subject name. `identity` is `(common_name, organizational_unit)`; when
+    /// absent both default to the network name, as used for the root CA's self-identity.
+    fn build_subject_name(&self, identity: Option<(&str, &str)>) -> Result<X509Name> {
+        let (common_name, unit_name) = identity.unwrap_or((&self.name, &self.name));
+
         let mut name_builder = openssl::x509::X509NameBuilder::new()?;
-        name_builder
-            .append_entry_by_nid(Nid::COMMONNAME, site.map(|s| &s.0).unwrap_or(&self.name))?;
+        name_builder.append_entry_by_nid(Nid::COMMONNAME, common_name)?;
         name_builder.append_entry_by_nid(Nid::ORGANIZATIONNAME, &self.name)?;
-        name_builder.append_entry_by_nid(
-            Nid::ORGANIZATIONALUNITNAME,
-            site.map(|s| &s.name).unwrap_or(&self.name),
-        )?;
+        name_builder.append_entry_by_nid(Nid::ORGANIZATIONALUNITNAME, unit_name)?;
         name_builder.append_entry_by_nid(Nid::PKCS9_EMAILADDRESS, &self.email)?;
 
         if let Some(country) = self.country.as_ref() {
@@ -120,13 +175,13 @@ fn load_or_generate_ca_cert(net: &Network) -> Result<Certificate> {
         bail!("Aborted by user");
     }
 
-    let key = Key::load_or_generate(&format!("{}.key", net.root_ca_name))?;
+    let key = Key::load_or_generate(&format!("{}.key", net.root_ca_name), net.key_type)?;
     let mut crt = CertificateBuilder::new(key)?;
     let subject = net.build_subject_name(None)?;
 
     crt.set_issuer_name(&subject)?;
     crt.set_subject_name(&subject)?;
-    crt.set_certificate_authority()?;
+    crt.set_certificate_authority(None)?;
     crt.set_validity_period(net.root_ca_validity_days)?;
     crt.sign_self()?;
 
@@ -135,6 +190,45 @@ fn load_or_generate_ca_cert(net: &Network) -> Result<Certificate> {
     return Ok(x509);
 }
 
+fn load_or_generate_intermediate_ca(net: &Network, root: &Certificate, ca: &CaCfg) -> Result<Certificate> {
+    if let Ok(crt) = Certificate::load(&ca.name) {
+        info!("Intermediate CA {} read OK", ca.name);
+        return Ok(crt);
+    }
+
+    info!("Intermediate CA {} does not exist", ca.name);
+    if !confirm(&format!(
+        "Certificate {} does not exist. Generate a new one?",
+        ca.name
+    )) {
+        bail!("Aborted by user");
+    }
+
+    let key = Key::load_or_generate(&format!("{}.key", ca.name), net.key_type)?;
+    let mut crt = CertificateBuilder::new(key)?;
+    let subject = net.build_subject_name(Some((&ca.name, &ca.name)))?;
+
+    crt.set_subject_name(&subject)?;
+    crt.set_issuer_name(root.subject_name())?;
+    crt.set_certificate_authority(Some(0))?;
+    crt.set_validity_period(ca.validity_days)?;
+
+    root.sign(&mut crt)?;
+
+    let x509 = crt.build();
+    x509.save(&ca.name)?;
+    Ok(x509)
+}
+
+fn generate_crl(net: &Network, ca_cert: &Certificate) -> Result<()> {
+    let revoked = RevocationList::load(REVOCATION_LIST_FILE_NAME)?;
+    let issuer = net.build_subject_name(None)?;
+
+    let crl = revoked.build(ca_cert, &issuer, net.crl_validity_days)?;
+    std::fs::write(format!("{}.crl", net.root_ca_name), crl.to_pem()?)?;
+    Ok(())
+}
+
 fn main() -> Result<()> {
     env_logger::Builder::from_default_env()
         .format_target(false)
@@ -158,25 +252,71 @@ fn main() -> Result<()> {
 
     let network = Network(config.network);
     let ca_cert = load_or_generate_ca_cert(&network)?;
+    generate_crl(&network, &ca_cert)?;
 
-    for (site_name, site_cfg) in config.sites {
-        let site = Site(site_name, site_cfg);
-
-        let site_key = Key::load_or_generate(&format!("{}.key", &site.0))?;
-        let site_crt = CertificateBuilder::new(site_key)?;
-        let mut site_crt = site_crt.set_server_auth()?;
+    let mut issuing_cas = HashMap::new();
+    for (ca_id, ca_cfg) in &network.cas {
+        let intermediate = load_or_generate_intermediate_ca(&network, &ca_cert, ca_cfg)?;
+        issuing_cas.insert(ca_id.clone(), intermediate);
+    }
 
-        // Set issuer and subject name
-        let subject = network.build_subject_name(Some(&site))?;
-        site_crt.set_subject_name(&subject)?;
-        site_crt.set_issuer_name(ca_cert.subject_name())?;
-        site_crt.set_validity_period(site.crt_validity_days)?;
-        site_crt.set_subject_alt_names(&site.alt_names)?;
+    let trust_roots: Vec<&Certificate> = vec![&ca_cert];
 
-        ca_cert.sign(&mut site_crt)?;
+    for (site_name, site_cfg) in config.sites {
+        let site = Site(site_name, site_cfg);
+        let issuer = match site.issuer.as_ref() {
+            Some(id) => issuing_cas
+                .get(id)
+                .with_context(|| format!("Unknown issuing CA '{}' referenced by site '{}'", id, site.0))?,
+            None => &ca_cert,
+        };
+
+        let x509 = if let Some(csr_path) = site.csr.as_ref() {
+            let pem = std::fs::read(csr_path)
+                .context(format!("Error reading certificate signing request {}", csr_path))?;
+            let req = X509Req::from_pem(&pem)?;
+            issuer.sign_request(
+                &req,
+                site.crt_validity_days,
+                site.purpose,
+                network.crl_url.as_deref(),
+            )?
+        } else {
+            let site_key = Key::load_or_generate(&format!("{}.key", &site.0), site.key_type.unwrap_or(network.key_type))?;
+            let site_crt = CertificateBuilder::new(site_key)?;
+            let mut site_crt = match site.purpose {
+                Purpose::Server => site_crt.set_server_auth()?,
+                Purpose::Client => site_crt.set_client_auth()?,
+                Purpose::Both => site_crt.set_server_and_client_auth()?,
+            };
+
+            // Set issuer and subject name
+            let subject = network.build_subject_name(Some((&site.0, &site.name)))?;
+            site_crt.set_subject_name(&subject)?;
+            site_crt.set_issuer_name(issuer.subject_name())?;
+            site_crt.set_validity_period(site.crt_validity_days)?;
+            site_crt.set_subject_alt_names(&site.alt_names)?;
+
+            if let Some(crl_url) = network.crl_url.as_ref() {
+                site_crt.append_extension(crl::crl_distribution_point_extension(crl_url)?)?;
+            }
+
+            issuer.sign(&mut site_crt)?;
+            site_crt.build()
+        };
+
+        if site.issuer.is_some() {
+            x509.save_with_chain(&site.0, &[issuer])?;
+        } else {
+            x509.save(&site.0)?;
+        }
 
-        let x509 = site_crt.build();
-        x509.save(&site.0)?;
+        let chain: Vec<&Certificate> = if site.issuer.is_some() { vec![issuer] } else { vec![] };
+        x509.verify_against(&trust_roots, &chain).context(format!(
+            "Issued certificate {} does not verify against the generated PKI",
+            site.0
+        ))?;
+        info!("Certificate {} verified OK", site.0);
     }
 
     Ok(())